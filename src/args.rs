@@ -4,14 +4,14 @@ use clap::{ArgEnum, Parser};
 #[clap(author, version, about, long_about = None)]
 pub struct Args {
     #[clap(
-        help = "The dialect/flavour of BBCode to emit.",
+        help = "The dialect/flavour of BBCode to emit. May be omitted if \
+                the input's front matter declares one.",
         short,
         long,
-        required = true,
         arg_enum,
         value_parser
     )]
-    pub dialect: Dialect,
+    pub dialect: Option<Dialect>,
     #[clap(
         help = "A path to the input Markdown file. Defaults to stdin.",
         short,
@@ -55,10 +55,60 @@ pub struct Args {
     pub tasklists: bool,
     #[clap(help = "Enable “smart punctuation”.", long, value_parser)]
     pub smart_punctuation: bool,
+    #[clap(
+        help = "Warn about output characters outside the UCS-2 range, for \
+                dialects that care (e.g. XenForo).",
+        long,
+        value_parser
+    )]
+    pub encoding_warnings: bool,
+    #[clap(
+        help = "Syntax-highlight code blocks with syntect, emitting \
+                colored spans ([color=...] for BBCode dialects, ANSI \
+                colors for the terminal preview) in place of the \
+                dialect's native code tag.",
+        long,
+        value_parser
+    )]
+    pub highlight: bool,
+    #[clap(
+        help = "Emit a linked table of contents, generated from the \
+                document's headings, at the top of the output.",
+        long,
+        value_parser
+    )]
+    pub toc: bool,
+    #[clap(
+        help = "Treat these flags as defaults instead of overrides: a \
+                document's front matter only fills in options this \
+                invocation didn't already turn on.",
+        long,
+        value_parser
+    )]
+    pub front_matter_defaults_only: bool,
+    #[clap(
+        help = "Don't convert to BBCode; instead pretty-print the \
+                Event/Tag stream pulldown-cmark produced for the input, \
+                for debugging conversion issues.",
+        long,
+        value_parser
+    )]
+    pub dump_events: bool,
+    #[clap(
+        help = "Don't emit BBCode; render straight to styled terminal \
+                output instead, so a document can be sanity-checked \
+                without opening a forum. Shorthand for `--dialect term`.",
+        long,
+        value_parser
+    )]
+    pub preview: bool,
 }
 
 #[derive(ArgEnum, Clone, Copy, PartialEq, Eq)]
 pub enum Dialect {
     Xenforo,
     Proboards,
+    /// Not a forum at all; renders to ANSI terminal escapes. Selected
+    /// directly with `--dialect term`, or via the `--preview` shorthand.
+    Term,
 }
@@ -1,19 +1,114 @@
 use crate::args::Dialect;
-use anyhow::{anyhow, bail, Result};
-use html_escape::decode_html_entities_to_string;
-use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag};
+use crate::dialect::{BbcodeDialect, Proboards, Term, Xenforo};
+use crate::idmap::IdMap;
+use crate::toc::{self, TocEntry};
+use anyhow::Result;
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
 
-static MULTILINE_SUMMARY: &str =
-    "A `<summary>` element (including its contents) must be all on a single \
-line";
+/// Builds the concrete dialect implementation for a given `args::Dialect`.
+fn dialect_impl(dialect: Dialect) -> Box<dyn BbcodeDialect> {
+    match dialect {
+        Dialect::Xenforo => Box::new(Xenforo),
+        Dialect::Proboards => Box::new(Proboards),
+        Dialect::Term => Box::new(Term),
+    }
+}
+
+/// Warns that `dialect` has no way to express `what`.
+fn warn_unsupported(dialect: &dyn BbcodeDialect, what: &str) {
+    eprintln!("[[WARN]] {} doesn't support {what}", dialect.name());
+}
+
+/// Pulls the language token out of a fenced code block's info string (the
+/// bit after the opening ` ``` `), lowercased and stripped of any trailing
+/// metadata, so that ` ```rust,ignore ` becomes `rust`. Returns `None` for
+/// an indented block or an empty/invalid info string.
+fn code_block_lang(kind: &CodeBlockKind) -> Option<String> {
+    let info = match kind {
+        CodeBlockKind::Fenced(info) => info,
+        CodeBlockKind::Indented => return None,
+    };
+
+    let token: String = info
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '+')
+        .collect();
+
+    if token.is_empty() {
+        None
+    } else {
+        Some(token.to_ascii_lowercase())
+    }
+}
+
+/// The `syntect` tables `highlight_code` needs, loaded once per `convert`
+/// call (not once per code block — parsing the full bundled syntax/theme
+/// set is expensive, and a document can have many fenced blocks).
+struct Highlighter {
+    syntax_set: syntect::parsing::SyntaxSet,
+    theme_set: syntect::highlighting::ThemeSet,
+}
+
+impl Highlighter {
+    fn new() -> Self {
+        Self {
+            syntax_set: syntect::parsing::SyntaxSet::load_defaults_newlines(),
+            theme_set: syntect::highlighting::ThemeSet::load_defaults(),
+        }
+    }
+}
+
+/// Syntax-highlights `code` with `syntect`, using `lang` (if recognised) to
+/// pick a syntax definition, and wraps each styled run with
+/// `dialect.color_span` so the result actually renders for whichever
+/// target is active (BBCode `[color]`, ANSI escapes, ...). Callers must not
+/// also wrap the block in the dialect's native `[code]`/`[pre]` tag: those
+/// render their contents verbatim, which would turn these spans into
+/// literal text instead of coloring anything.
+fn highlight_code(
+    highlighter: &Highlighter,
+    dialect: &dyn BbcodeDialect,
+    code: &str,
+    lang: Option<&str>,
+) -> String {
+    use syntect::easy::HighlightLines;
+    use syntect::util::LinesWithEndings;
+
+    let syntax = lang
+        .and_then(|lang| highlighter.syntax_set.find_syntax_by_token(lang))
+        .unwrap_or_else(|| highlighter.syntax_set.find_syntax_plain_text());
+
+    let mut highlight_lines =
+        HighlightLines::new(syntax, &highlighter.theme_set.themes["base16-ocean.dark"]);
+
+    let mut out = String::with_capacity(code.len());
+    for line in LinesWithEndings::from(code) {
+        let Ok(ranges) = highlight_lines.highlight_line(line, &highlighter.syntax_set) else {
+            out.push_str(line);
+            continue;
+        };
+
+        for (style, text) in ranges {
+            let rgb = (style.foreground.r, style.foreground.g, style.foreground.b);
+            out.push_str(&dialect.color_span(rgb, text));
+        }
+    }
+
+    out
+}
 
 pub fn convert<S: AsRef<str>>(
     input: S,
     dialect: Dialect,
     encoding_warnings: bool,
+    highlight: bool,
+    toc: bool,
     markdown_opts: Options,
 ) -> Result<String> {
     let input = input.as_ref();
+    let dialect = dialect_impl(dialect);
+    let dialect = dialect.as_ref();
+    let highlighter = highlight.then(Highlighter::new);
 
     // Set up the Markdown parser.
     let parser = Parser::new_ext(input, markdown_opts);
@@ -22,7 +117,7 @@ pub fn convert<S: AsRef<str>>(
     let mut output = String::with_capacity(input.len());
 
     // This state machine just iterates through the events pulled from the
-    // Markdown parser.
+    // Markdown parser, dispatching each one to the active `BbcodeDialect`.
     //
     // `start_li` is a bit of state required to handle items in (ordered or
     // unordered) lists, so that we can emit both `[li]` _and_ `[/li]`, if the
@@ -30,6 +125,17 @@ pub fn convert<S: AsRef<str>>(
     // definitions.
     let mut start_li = false;
     let mut start_fn = false;
+    // While inside a code block and `--highlight` is on, code text is
+    // buffered here instead of going straight to `output`, so that the whole
+    // block's body can be run through `syntect` at once at `Tag::CodeBlock`'s
+    // `Event::End`.
+    let mut code_buffer: Option<(Option<String>, String)> = None;
+    // While inside a heading and `--toc` is on, its plain text (no BBCode
+    // markup) is accumulated here so an anchor slug can be derived once the
+    // heading ends.
+    let mut heading_text: Option<String> = None;
+    let mut idmap = IdMap::new();
+    let mut toc_entries: Vec<TocEntry> = Vec::new();
     for event in parser {
         match event {
             Event::Start(tag) => match tag {
@@ -41,205 +147,136 @@ pub fn convert<S: AsRef<str>>(
                         output.push('\n')
                     }
                 }
-                // We can ignore the fragment ID and the element’s classes,
+                // We can ignore the fragment ID and the element's classes,
                 // respectively; BBCode will have nothing to do with such
                 // information.
                 Tag::Heading(lvl, _, _) => {
-                    // We emulate actual headers (`<h1>`, `<h2>`, etc.) by
-                    // increasing font size, making the text bold, and
-                    // underlining the text.
-                    output.push_str(match dialect {
-                        Dialect::Xenforo => "\n[size=\"",
-                        Dialect::Proboards => "\n\n[font size=\"",
-                    });
-                    output.push(match lvl {
-                        HeadingLevel::H1 => '7',
-                        HeadingLevel::H2 => '6',
-                        HeadingLevel::H3 => '5',
-                        HeadingLevel::H4 => '4',
-                        _ => '3',
-                    });
-                    output.push_str("\"][b][u]");
-                }
-                Tag::BlockQuote => output.push_str(match dialect {
-                    Dialect::Xenforo => "[quote]",
-                    Dialect::Proboards => "[blockquote]",
-                }),
-                // We ignore the specified code language, if any.
-                Tag::CodeBlock(_) => output.push_str(match dialect {
-                    Dialect::Xenforo => "[code]",
-                    Dialect::Proboards => "\n[pre]",
-                }),
-                Tag::List(ord) => output.push_str(if ord.is_some() {
-                    // It might seem weird that we don’t inspect the value
-                    // inside of `ord`, but AFAIK, no BBCode implementations
-                    // properly implement a “starting number” for `<ol>`s.
-                    match dialect {
-                        Dialect::Xenforo => "[list=1]",
-                        Dialect::Proboards => "\n[ol]",
+                    output.push_str(&dialect.heading_start(lvl));
+                    if toc {
+                        heading_text = Some(String::new());
                     }
-                } else {
-                    match dialect {
-                        Dialect::Xenforo => "[list]",
-                        Dialect::Proboards => "\n[ul]",
+                }
+                Tag::BlockQuote => output.push_str(&dialect.blockquote_start()),
+                Tag::CodeBlock(kind) => {
+                    let lang = code_block_lang(&kind);
+                    if highlighter.is_some() {
+                        // The dialect's native `[code=lang]`/`[pre]` tag
+                        // renders its contents verbatim, which would turn
+                        // the highlighted `color_span`s below into literal
+                        // text. Skip it and let the spans stand on their
+                        // own, bracketed by blank lines like a block
+                        // normally would be.
+                        output.push('\n');
+                        code_buffer = Some((lang, String::new()));
+                    } else {
+                        output.push_str(&dialect.code_block_start(lang.as_deref()));
                     }
-                }),
+                }
+                Tag::List(ord) => output.push_str(&dialect.list_start(ord.is_some())),
                 Tag::Item => {
                     start_li = true;
-                    output.push_str(match dialect {
-                        Dialect::Xenforo => "\n[*]",
-                        Dialect::Proboards => "\n[li]",
-                    });
+                    output.push_str(&dialect.item_start());
                 }
                 Tag::FootnoteDefinition(fnid) => {
                     start_fn = true;
-
-                    // We do our best to emulate a footnote definition...
-                    output.push_str("\n\u{231c}"); // ⌜
-                    output.push_str(&fnid);
-                    output.push_str("\u{231d}: ");
+                    output.push_str(&dialect.footnote_definition_start(&fnid));
                 }
                 // We ignore alignment indicators for tables, because again,
                 // BBCode cannot do anything with this information.
-                Tag::Table(_) => output.push_str("[table]"),
-                Tag::TableHead => output.push_str(match dialect {
-                    Dialect::Xenforo => "[tr]",
-                    Dialect::Proboards => "\n  [thead][tr]",
-                }),
-                Tag::TableRow => output.push_str("[tr]"),
-                Tag::TableCell => output.push_str("[td]"),
-                Tag::Emphasis => output.push_str("[i]"),
-                Tag::Strong => output.push_str("[b]"),
-                Tag::Strikethrough => output.push_str("[s]"),
-                // Link type and anchor title, respectively, don’t matter...
-                Tag::Link(_, url, _) => {
-                    output.push_str(match dialect {
-                        Dialect::Xenforo => "[url=",
-                        Dialect::Proboards => "[a href=\"",
-                    });
-                    output.push_str(&url);
-                    output.push_str(match dialect {
-                        Dialect::Xenforo => "]",
-                        Dialect::Proboards => "\"]",
-                    });
-                }
-                // Link type still don’t matter.
-                Tag::Image(_, url, title) => match dialect {
-                    Dialect::Xenforo => {
-                        output.push_str("[img]");
-                        output.push_str(&url);
-                        output.push_str("[/img]");
-                    }
-                    Dialect::Proboards => {
-                        output.push_str("[img src=\"");
-                        output.push_str(&url);
-                        output.push_str("\" alt=\"");
-                        output.push_str(&title);
-                        output.push_str("\"]");
-                    }
-                },
+                Tag::Table(_) => output.push_str(&dialect.table_start()),
+                Tag::TableHead => output.push_str(&dialect.table_head_start()),
+                Tag::TableRow => output.push_str(&dialect.table_row_start()),
+                Tag::TableCell => output.push_str(&dialect.table_cell_start()),
+                Tag::Emphasis => output.push_str(&dialect.emphasis_start()),
+                Tag::Strong => output.push_str(&dialect.strong_start()),
+                Tag::Strikethrough => output.push_str(&dialect.strikethrough_start()),
+                // Link type and anchor title, respectively, don't matter...
+                Tag::Link(_, url, _) => output.push_str(&dialect.link_start(&url)),
+                // Link type still doesn't matter.
+                Tag::Image(_, url, title) => output.push_str(&dialect.image(&url, &title)),
             },
             Event::End(tag) => match tag {
                 Tag::Paragraph => output.push('\n'),
-                Tag::Heading(_, _, _) => output.push_str(match dialect {
-                    Dialect::Xenforo => "[/u][/b][/size]\n",
-                    Dialect::Proboards => "[/u][/b][/font]\n\n",
-                }),
-                Tag::BlockQuote => output.push_str(match dialect {
-                    Dialect::Xenforo => "[/quote]",
-                    Dialect::Proboards => "[/blockquote]",
-                }),
-                Tag::CodeBlock(_) => output.push_str(match dialect {
-                    Dialect::Xenforo => "[/code]\n",
-                    Dialect::Proboards => "[/pre]\n",
-                }),
-                Tag::List(ord) => output.push_str(match dialect {
-                    Dialect::Xenforo => "\n[/list]",
-                    Dialect::Proboards => {
-                        if ord.is_some() {
-                            "\n[/ol]"
-                        } else {
-                            "\n[/ul]"
-                        }
+                Tag::Heading(lvl, _, _) => {
+                    if toc {
+                        let text = heading_text.take().unwrap_or_default();
+                        let slug = idmap.derive_id(&text);
+                        output.push_str(&dialect.heading_anchor(&slug));
+                        toc_entries.push((lvl, slug, text));
                     }
-                }),
+                    output.push_str(&dialect.heading_end());
+                }
+                Tag::BlockQuote => output.push_str(&dialect.blockquote_end()),
+                Tag::CodeBlock(_) => {
+                    if let Some((lang, code)) = code_buffer.take() {
+                        let highlighter = highlighter
+                            .as_ref()
+                            .expect("code_buffer is only set when highlighting");
+                        output.push_str(&highlight_code(
+                            highlighter,
+                            dialect,
+                            &code,
+                            lang.as_deref(),
+                        ));
+                        output.push('\n');
+                    } else {
+                        output.push_str(&dialect.code_block_end());
+                    }
+                }
+                Tag::List(ord) => output.push_str(&dialect.list_end(ord.is_some())),
                 Tag::Item => {
                     // A smol hack to make the whitespace around list items not
-                    // get goof’d up.
+                    // get goof'd up.
                     output.truncate(output.trim_end().len());
-
-                    match dialect {
-                        Dialect::Xenforo => (),
-                        Dialect::Proboards => output.push_str("[/li]"),
-                    }
+                    output.push_str(&dialect.item_end());
                 }
-                Tag::FootnoteDefinition(_) => output.push('\n'),
-                // Once again, ignoring table column alignments...
-                Tag::Table(_) => output.push_str(match dialect {
-                    Dialect::Xenforo => "[/table]",
-                    Dialect::Proboards => "\n  [/tbody]\n[/table]",
-                }),
-                Tag::TableHead => output.push_str(match dialect {
-                    Dialect::Xenforo => "[/tr]",
-                    Dialect::Proboards => "[/tr][/thead]\n  [tbody]",
-                }),
-                Tag::TableRow => output.push_str("[/tr]"),
-                Tag::TableCell => output.push_str("[/td]"),
-                Tag::Emphasis => output.push_str("[/i]"),
-                Tag::Strong => output.push_str("[/b]"),
-                Tag::Strikethrough => output.push_str("[/s]"),
-                Tag::Link(_, _, _) => output.push_str(match dialect {
-                    Dialect::Xenforo => "[/url]",
-                    Dialect::Proboards => "[/a]",
-                }),
+                Tag::FootnoteDefinition(_) => output.push_str(&dialect.footnote_definition_end()),
+                Tag::Table(_) => output.push_str(&dialect.table_end()),
+                Tag::TableHead => output.push_str(&dialect.table_head_end()),
+                Tag::TableRow => output.push_str(&dialect.table_row_end()),
+                Tag::TableCell => output.push_str(&dialect.table_cell_end()),
+                Tag::Emphasis => output.push_str(&dialect.emphasis_end()),
+                Tag::Strong => output.push_str(&dialect.strong_end()),
+                Tag::Strikethrough => output.push_str(&dialect.strikethrough_end()),
+                Tag::Link(_, _, _) => output.push_str(&dialect.link_end()),
                 // No need to handle the end of an image element; the handler
                 // for the start of an image element (as seen above) does all
                 // of the work.
                 Tag::Image(_, _, _) => (),
             },
-            Event::Text(s) => output.push_str(&s),
+            Event::Text(s) => {
+                if let Some(buf) = &mut heading_text {
+                    buf.push_str(&s);
+                }
+                match &mut code_buffer {
+                    Some((_, code)) => code.push_str(&s),
+                    None => output.push_str(&s),
+                }
+            }
             Event::Code(s) => {
-                // Yes, the fact that we specify the font as `Courier New` to
-                // implement inline “code” elements for XenForo is deeply
-                // unfortunate. But I don’t know of any better way.
-                output.push_str(match dialect {
-                    Dialect::Xenforo => "[font=Courier New]",
-                    Dialect::Proboards => "[tt]",
-                });
-                output.push_str(&s);
-                output.push_str(match dialect {
-                    Dialect::Xenforo => "[/font]",
-                    Dialect::Proboards => "[/tt]",
-                });
+                if let Some(buf) = &mut heading_text {
+                    buf.push_str(&s);
+                }
+                output.push_str(&dialect.code_inline(&s));
             }
             Event::Html(s) => {
                 match s.as_ref().trim() {
                     // Some particular HTML elements have known translations:
-                    "<del>" => output.push_str("[s]"),
-                    "</del>" => output.push_str("[/s]"),
-                    "<sup>" => output.push_str("[sup]"),
-                    "</sup>" => output.push_str("[/sup]"),
-                    "<sub>" => output.push_str("[sub]"),
-                    "</sub>" => output.push_str("[/sub]"),
-                    "<b>" => output.push_str("[b]"),
-                    "</b>" => output.push_str("[/b]"),
-                    "<i>" => output.push_str("[i]"),
-                    "</i>" => output.push_str("[/i]"),
-                    "<blockquote>" => output.push_str(match dialect {
-                        Dialect::Xenforo => "[quote]",
-                        Dialect::Proboards => "[blockquote]",
-                    }),
-                    "</blockquote>" => output.push_str(match dialect {
-                        Dialect::Xenforo => "[/quote]",
-                        Dialect::Proboards => "[/blockquote]",
-                    }),
-                    "<details>" => match dialect {
-                        Dialect::Xenforo => output.push_str("\n[spoiler="),
-                        // ProBoards doesn’t have details/spoiler elements
-                        // AFAIK, so we just skip it.
-                        Dialect::Proboards => eprintln!(
-                            "[[WARN]] ProBoards doesn’t support `<details>`",
-                        ),
+                    "<del>" => output.push_str(&dialect.strikethrough_start()),
+                    "</del>" => output.push_str(&dialect.strikethrough_end()),
+                    "<sup>" => output.push_str(&dialect.superscript_start()),
+                    "</sup>" => output.push_str(&dialect.superscript_end()),
+                    "<sub>" => output.push_str(&dialect.subscript_start()),
+                    "</sub>" => output.push_str(&dialect.subscript_end()),
+                    "<b>" => output.push_str(&dialect.strong_start()),
+                    "</b>" => output.push_str(&dialect.strong_end()),
+                    "<i>" => output.push_str(&dialect.emphasis_start()),
+                    "</i>" => output.push_str(&dialect.emphasis_end()),
+                    "<blockquote>" => output.push_str(&dialect.blockquote_start()),
+                    "</blockquote>" => output.push_str(&dialect.blockquote_end()),
+                    "<details>" => match dialect.spoiler_start()? {
+                        Some(snippet) => output.push_str(&snippet),
+                        None => warn_unsupported(dialect, "`<details>`"),
                     },
                     s_trimmed if s_trimmed.starts_with("<br") => {
                         let mut is_br = true;
@@ -263,37 +300,22 @@ pub fn convert<S: AsRef<str>>(
                         }
                     }
                     s_trimmed if s_trimmed.starts_with("<summary") => {
-                        match dialect {
-                            Dialect::Xenforo => {
-                                if !s_trimmed.ends_with("</summary>") {
-                                    bail!(MULTILINE_SUMMARY);
-                                }
-
-                                decode_html_entities_to_string(
-                                    &s_trimmed
-                                        .split(&['<', '>'][..])
-                                        .nth(2)
-                                        .ok_or_else(|| {
-                                        anyhow!(MULTILINE_SUMMARY)
-                                    })?,
-                                    &mut output,
-                                );
-                                output.push(']');
-                            }
-                            Dialect::Proboards => (),
+                        if let Some(snippet) = dialect.spoiler_summary(s_trimmed)? {
+                            output.push_str(&snippet);
+                        }
+                    }
+                    "</details>" => {
+                        if let Some(snippet) = dialect.spoiler_end() {
+                            output.push_str(&snippet);
                         }
                     }
-                    "</details>" => match dialect {
-                        Dialect::Xenforo => output.push_str("[/spoiler]\n"),
-                        Dialect::Proboards => (),
-                    },
                     _ => {
                         // Any HTML elements that start with `<!` are assumed
                         // to be comments of some kind.
                         if !s.starts_with("<!") {
                             eprintln!("[[WARN]] Unrecognised HTML tag: {s}");
-                            // This isn’t a comment, so we assume that this
-                            // “HTML element” is not an HTML element at all,
+                            // This isn't a comment, so we assume that this
+                            // "HTML element" is not an HTML element at all,
                             // and is meant to be interpreted literally!
                             output.push_str(&s);
                         }
@@ -301,26 +323,11 @@ pub fn convert<S: AsRef<str>>(
                 }
             }
             Event::FootnoteReference(fnid) => {
-                // We do our best to emulate a footnote marker...
-                match dialect {
-                    Dialect::Xenforo => (),
-                    Dialect::Proboards => output.push_str("[sup]"),
-                }
-                output.push('\u{231c}'); // ⌜
-                output.push_str(&fnid);
-                output.push('\u{231d}'); // ⌝
-                match dialect {
-                    Dialect::Xenforo => (),
-                    Dialect::Proboards => output.push_str("[/sup]"),
-                }
+                output.push_str(&dialect.footnote_reference(&fnid));
             }
             Event::SoftBreak => output.push(' '),
             Event::HardBreak => output.push('\n'),
-            Event::Rule => output.push_str(match dialect {
-                // Shitty hack for XenForo LMAO
-                Dialect::Xenforo => "\n━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━\n",
-                Dialect::Proboards => "\n[hr]\n",
-            }),
+            Event::Rule => output.push_str(&dialect.rule()),
             Event::TaskListMarker(checked) => {
                 output.push(if checked {
                     '\u{2611}' // BALLOT BOX WITH CHECK
@@ -332,22 +339,23 @@ pub fn convert<S: AsRef<str>>(
         }
     }
 
-    if encoding_warnings {
-        match dialect {
-            Dialect::Xenforo => {
-                for c in output.chars() {
-                    if c >= '\u{fffe}' {
-                        eprintln!(
-                            "[[WARN]] Non-UCS-2 character in output: '{c}' \
-                             (U+{:x})",
-                            u32::from(c),
-                        );
-                    }
-                }
+    if encoding_warnings && dialect.warn_on_non_ucs2() {
+        for c in output.chars() {
+            if c >= '\u{fffe}' {
+                eprintln!(
+                    "[[WARN]] Non-UCS-2 character in output: '{c}' (U+{:x})",
+                    u32::from(c),
+                );
             }
-            Dialect::Proboards => (),
         }
     }
 
+    if toc && !toc_entries.is_empty() {
+        let mut with_toc = toc::render(dialect, &toc_entries);
+        with_toc.push('\n');
+        with_toc.push_str(&output);
+        return Ok(with_toc);
+    }
+
     Ok(output)
 }
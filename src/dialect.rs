@@ -0,0 +1,705 @@
+//! Per-forum BBCode dialects.
+//!
+//! `convert::convert` used to be one big state machine where every
+//! `Event`/`Tag` arm contained a `match dialect { Xenforo => ..., Proboards
+//! => ... }`. That made adding a new forum (phpBB, vBulletin, SMF,
+//! Discourse, ...) a matter of hunting down every scattered arm. Instead,
+//! each forum gets one `impl BbcodeDialect`, modelled on orgize's
+//! `HtmlHandler`: one method per Markdown construct, each producing the
+//! BBCode snippet (or signalling that the construct isn't supported).
+
+use anyhow::{anyhow, bail, Result};
+use html_escape::decode_html_entities_to_string;
+use pulldown_cmark::HeadingLevel;
+
+static MULTILINE_SUMMARY: &str =
+    "A `<summary>` element (including its contents) must be all on a single \
+line";
+
+/// One method per Markdown construct that needs translating into BBCode.
+///
+/// Most methods simply return the snippet to emit. A few return
+/// `Option`/`Result<Option<_>>` for constructs that a given forum can't
+/// express at all (e.g. ProBoards has no spoiler/details equivalent); in
+/// that case `convert` reports "unsupported" instead of silently dropping
+/// content.
+pub trait BbcodeDialect {
+    /// A human-readable name, used in "unsupported" warnings.
+    fn name(&self) -> &'static str;
+
+    fn heading_start(&self, level: HeadingLevel) -> String;
+    fn heading_end(&self) -> String;
+
+    fn blockquote_start(&self) -> String;
+    fn blockquote_end(&self) -> String;
+
+    /// `lang` is the lowercased, validated language token from a fenced
+    /// code block's info string (e.g. `rust` out of ` ```rust,ignore `), if
+    /// any. Dialects with no notion of a language attribute should ignore
+    /// it.
+    fn code_block_start(&self, lang: Option<&str>) -> String;
+    fn code_block_end(&self) -> String;
+
+    fn list_start(&self, ordered: bool) -> String;
+    fn list_end(&self, ordered: bool) -> String;
+
+    fn item_start(&self) -> String;
+    /// Called after trailing whitespace around the item has already been
+    /// trimmed off the output buffer.
+    fn item_end(&self) -> String;
+
+    fn footnote_definition_start(&self, fnid: &str) -> String;
+    fn footnote_definition_end(&self) -> String;
+    fn footnote_reference(&self, fnid: &str) -> String;
+
+    fn table_start(&self) -> String;
+    fn table_end(&self) -> String;
+    fn table_head_start(&self) -> String;
+    fn table_head_end(&self) -> String;
+    fn table_row_start(&self) -> String;
+    fn table_row_end(&self) -> String;
+    fn table_cell_start(&self) -> String;
+    fn table_cell_end(&self) -> String;
+
+    fn emphasis_start(&self) -> String;
+    fn emphasis_end(&self) -> String;
+    fn strong_start(&self) -> String;
+    fn strong_end(&self) -> String;
+    fn strikethrough_start(&self) -> String;
+    fn strikethrough_end(&self) -> String;
+    fn superscript_start(&self) -> String;
+    fn superscript_end(&self) -> String;
+    fn subscript_start(&self) -> String;
+    fn subscript_end(&self) -> String;
+
+    fn code_inline(&self, code: &str) -> String;
+
+    fn link_start(&self, url: &str) -> String;
+    fn link_end(&self) -> String;
+    fn image(&self, url: &str, title: &str) -> String;
+
+    fn rule(&self) -> String;
+
+    /// An invisible `#slug`-addressable anchor to drop next to a heading, so
+    /// that a table of contents can link straight to it. Built from
+    /// `link_start`/`link_end`, so any dialect that can link somewhere gets
+    /// one for free.
+    fn heading_anchor(&self, slug: &str) -> String {
+        format!("{}{}", self.link_start(&format!("#{slug}")), self.link_end())
+    }
+
+    /// `Ok(None)` means this dialect has no spoiler/details construct at
+    /// all; `convert` then skips the wrapper but still renders the block's
+    /// contents.
+    fn spoiler_start(&self) -> Result<Option<String>>;
+    /// Receives the trimmed `<summary>...</summary>` HTML, tags included.
+    /// XenForo requires it to be on a single line, strips the tags, and
+    /// HTML-entity-decodes the text for use as the `[spoiler=...]` title.
+    fn spoiler_summary(&self, summary_trimmed: &str) -> Result<Option<String>>;
+    fn spoiler_end(&self) -> Option<String>;
+
+    /// Whether characters outside the UCS-2 range should trigger
+    /// `encoding_warnings` output. XenForo historically chokes on these;
+    /// most other forums don't care.
+    fn warn_on_non_ucs2(&self) -> bool {
+        false
+    }
+
+    /// Wraps `text` so it renders in the given foreground color. Used by
+    /// `--highlight` to paint `syntect`'s output; the default emits the
+    /// generic `[color=#rrggbb]` BBCode tag that both forum dialects
+    /// understand, but a non-BBCode target like `Term` overrides this with
+    /// its own notion of color.
+    fn color_span(&self, rgb: (u8, u8, u8), text: &str) -> String {
+        let (r, g, b) = rgb;
+        format!("[color=#{r:02x}{g:02x}{b:02x}]{text}[/color]")
+    }
+}
+
+pub struct Xenforo;
+
+impl BbcodeDialect for Xenforo {
+    fn name(&self) -> &'static str {
+        "XenForo"
+    }
+
+    fn heading_start(&self, level: HeadingLevel) -> String {
+        // We emulate actual headers (`<h1>`, `<h2>`, etc.) by increasing
+        // font size, making the text bold, and underlining the text.
+        let size = match level {
+            HeadingLevel::H1 => '7',
+            HeadingLevel::H2 => '6',
+            HeadingLevel::H3 => '5',
+            HeadingLevel::H4 => '4',
+            _ => '3',
+        };
+        format!("\n[size=\"{size}\"][b][u]")
+    }
+
+    fn heading_end(&self) -> String {
+        "[/u][/b][/size]\n".to_string()
+    }
+
+    fn blockquote_start(&self) -> String {
+        "[quote]".to_string()
+    }
+
+    fn blockquote_end(&self) -> String {
+        "[/quote]".to_string()
+    }
+
+    fn code_block_start(&self, lang: Option<&str>) -> String {
+        match lang {
+            Some(lang) => format!("[code={lang}]"),
+            None => "[code]".to_string(),
+        }
+    }
+
+    fn code_block_end(&self) -> String {
+        "[/code]\n".to_string()
+    }
+
+    fn list_start(&self, ordered: bool) -> String {
+        // It might seem weird that we don't take a starting number for
+        // ordered lists, but AFAIK, no BBCode implementations properly
+        // implement this.
+        if ordered {
+            "[list=1]".to_string()
+        } else {
+            "[list]".to_string()
+        }
+    }
+
+    fn list_end(&self, _ordered: bool) -> String {
+        "\n[/list]".to_string()
+    }
+
+    fn item_start(&self) -> String {
+        "\n[*]".to_string()
+    }
+
+    fn item_end(&self) -> String {
+        String::new()
+    }
+
+    fn footnote_definition_start(&self, fnid: &str) -> String {
+        // We do our best to emulate a footnote definition...
+        format!("\n\u{231c}{fnid}\u{231d}: ") // ⌜...⌝
+    }
+
+    fn footnote_definition_end(&self) -> String {
+        "\n".to_string()
+    }
+
+    fn footnote_reference(&self, fnid: &str) -> String {
+        format!("\u{231c}{fnid}\u{231d}") // ⌜...⌝
+    }
+
+    fn table_start(&self) -> String {
+        "[table]".to_string()
+    }
+
+    fn table_end(&self) -> String {
+        "[/table]".to_string()
+    }
+
+    fn table_head_start(&self) -> String {
+        "[tr]".to_string()
+    }
+
+    fn table_head_end(&self) -> String {
+        "[/tr]".to_string()
+    }
+
+    fn table_row_start(&self) -> String {
+        "[tr]".to_string()
+    }
+
+    fn table_row_end(&self) -> String {
+        "[/tr]".to_string()
+    }
+
+    fn table_cell_start(&self) -> String {
+        "[td]".to_string()
+    }
+
+    fn table_cell_end(&self) -> String {
+        "[/td]".to_string()
+    }
+
+    fn emphasis_start(&self) -> String {
+        "[i]".to_string()
+    }
+
+    fn emphasis_end(&self) -> String {
+        "[/i]".to_string()
+    }
+
+    fn strong_start(&self) -> String {
+        "[b]".to_string()
+    }
+
+    fn strong_end(&self) -> String {
+        "[/b]".to_string()
+    }
+
+    fn strikethrough_start(&self) -> String {
+        "[s]".to_string()
+    }
+
+    fn strikethrough_end(&self) -> String {
+        "[/s]".to_string()
+    }
+
+    fn superscript_start(&self) -> String {
+        "[sup]".to_string()
+    }
+
+    fn superscript_end(&self) -> String {
+        "[/sup]".to_string()
+    }
+
+    fn subscript_start(&self) -> String {
+        "[sub]".to_string()
+    }
+
+    fn subscript_end(&self) -> String {
+        "[/sub]".to_string()
+    }
+
+    fn code_inline(&self, code: &str) -> String {
+        // Yes, the fact that we specify the font as `Courier New` to
+        // implement inline "code" elements for XenForo is deeply
+        // unfortunate. But I don't know of any better way.
+        format!("[font=Courier New]{code}[/font]")
+    }
+
+    fn link_start(&self, url: &str) -> String {
+        format!("[url={url}]")
+    }
+
+    fn link_end(&self) -> String {
+        "[/url]".to_string()
+    }
+
+    fn image(&self, url: &str, _title: &str) -> String {
+        format!("[img]{url}[/img]")
+    }
+
+    fn rule(&self) -> String {
+        // Shitty hack for XenForo LMAO
+        "\n━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━\n".to_string()
+    }
+
+    fn spoiler_start(&self) -> Result<Option<String>> {
+        Ok(Some("\n[spoiler=".to_string()))
+    }
+
+    fn spoiler_summary(&self, summary_trimmed: &str) -> Result<Option<String>> {
+        if !summary_trimmed.ends_with("</summary>") {
+            bail!(MULTILINE_SUMMARY);
+        }
+
+        let mut title = String::new();
+        decode_html_entities_to_string(
+            &summary_trimmed
+                .split(&['<', '>'][..])
+                .nth(2)
+                .ok_or_else(|| anyhow!(MULTILINE_SUMMARY))?,
+            &mut title,
+        );
+        title.push(']');
+
+        Ok(Some(title))
+    }
+
+    fn spoiler_end(&self) -> Option<String> {
+        Some("[/spoiler]\n".to_string())
+    }
+
+    fn warn_on_non_ucs2(&self) -> bool {
+        true
+    }
+}
+
+pub struct Proboards;
+
+impl BbcodeDialect for Proboards {
+    fn name(&self) -> &'static str {
+        "ProBoards"
+    }
+
+    fn heading_start(&self, level: HeadingLevel) -> String {
+        let size = match level {
+            HeadingLevel::H1 => '7',
+            HeadingLevel::H2 => '6',
+            HeadingLevel::H3 => '5',
+            HeadingLevel::H4 => '4',
+            _ => '3',
+        };
+        format!("\n\n[font size=\"{size}\"][b][u]")
+    }
+
+    fn heading_end(&self) -> String {
+        "[/u][/b][/font]\n\n".to_string()
+    }
+
+    fn blockquote_start(&self) -> String {
+        "[blockquote]".to_string()
+    }
+
+    fn blockquote_end(&self) -> String {
+        "[/blockquote]".to_string()
+    }
+
+    fn code_block_start(&self, _lang: Option<&str>) -> String {
+        // ProBoards' `[pre]` tag has no language attribute.
+        "\n[pre]".to_string()
+    }
+
+    fn code_block_end(&self) -> String {
+        "[/pre]\n".to_string()
+    }
+
+    fn list_start(&self, ordered: bool) -> String {
+        if ordered {
+            "\n[ol]".to_string()
+        } else {
+            "\n[ul]".to_string()
+        }
+    }
+
+    fn list_end(&self, ordered: bool) -> String {
+        if ordered {
+            "\n[/ol]".to_string()
+        } else {
+            "\n[/ul]".to_string()
+        }
+    }
+
+    fn item_start(&self) -> String {
+        "\n[li]".to_string()
+    }
+
+    fn item_end(&self) -> String {
+        "[/li]".to_string()
+    }
+
+    fn footnote_definition_start(&self, fnid: &str) -> String {
+        format!("\n\u{231c}{fnid}\u{231d}: ") // ⌜...⌝
+    }
+
+    fn footnote_definition_end(&self) -> String {
+        "\n".to_string()
+    }
+
+    fn footnote_reference(&self, fnid: &str) -> String {
+        format!("[sup]\u{231c}{fnid}\u{231d}[/sup]") // ⌜...⌝
+    }
+
+    fn table_start(&self) -> String {
+        "[table]".to_string()
+    }
+
+    fn table_end(&self) -> String {
+        "\n  [/tbody]\n[/table]".to_string()
+    }
+
+    fn table_head_start(&self) -> String {
+        "\n  [thead][tr]".to_string()
+    }
+
+    fn table_head_end(&self) -> String {
+        "[/tr][/thead]\n  [tbody]".to_string()
+    }
+
+    fn table_row_start(&self) -> String {
+        "[tr]".to_string()
+    }
+
+    fn table_row_end(&self) -> String {
+        "[/tr]".to_string()
+    }
+
+    fn table_cell_start(&self) -> String {
+        "[td]".to_string()
+    }
+
+    fn table_cell_end(&self) -> String {
+        "[/td]".to_string()
+    }
+
+    fn emphasis_start(&self) -> String {
+        "[i]".to_string()
+    }
+
+    fn emphasis_end(&self) -> String {
+        "[/i]".to_string()
+    }
+
+    fn strong_start(&self) -> String {
+        "[b]".to_string()
+    }
+
+    fn strong_end(&self) -> String {
+        "[/b]".to_string()
+    }
+
+    fn strikethrough_start(&self) -> String {
+        "[s]".to_string()
+    }
+
+    fn strikethrough_end(&self) -> String {
+        "[/s]".to_string()
+    }
+
+    fn superscript_start(&self) -> String {
+        "[sup]".to_string()
+    }
+
+    fn superscript_end(&self) -> String {
+        "[/sup]".to_string()
+    }
+
+    fn subscript_start(&self) -> String {
+        "[sub]".to_string()
+    }
+
+    fn subscript_end(&self) -> String {
+        "[/sub]".to_string()
+    }
+
+    fn code_inline(&self, code: &str) -> String {
+        format!("[tt]{code}[/tt]")
+    }
+
+    fn link_start(&self, url: &str) -> String {
+        format!("[a href=\"{url}\"]")
+    }
+
+    fn link_end(&self) -> String {
+        "[/a]".to_string()
+    }
+
+    fn image(&self, url: &str, title: &str) -> String {
+        format!("[img src=\"{url}\" alt=\"{title}\"]")
+    }
+
+    fn rule(&self) -> String {
+        "\n[hr]\n".to_string()
+    }
+
+    fn spoiler_start(&self) -> Result<Option<String>> {
+        // ProBoards doesn't have details/spoiler elements AFAIK.
+        Ok(None)
+    }
+
+    fn spoiler_summary(&self, _summary_trimmed: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn spoiler_end(&self) -> Option<String> {
+        None
+    }
+
+    // Uses the default `[color=#rrggbb]` BBCode span; see `Term` below for
+    // the ANSI equivalent.
+}
+
+/// Renders straight to styled terminal output instead of BBCode, borrowing
+/// rustc_errors' `markdown/term.rs` idea: bold for `Strong`, italic for
+/// `Emphasis`, dim for `BlockQuote`, reverse video for code, and OSC-8
+/// hyperlinks for `Link`. Because this is just another `BbcodeDialect` impl,
+/// `convert` drives it with the exact same list/footnote/TOC bookkeeping it
+/// uses for every other forum — nothing about the event walk is duplicated.
+pub struct Term;
+
+impl Term {
+    const BOLD: &'static str = "\x1b[1m";
+    const NO_BOLD: &'static str = "\x1b[22m";
+    const ITALIC: &'static str = "\x1b[3m";
+    const NO_ITALIC: &'static str = "\x1b[23m";
+    const UNDERLINE: &'static str = "\x1b[4m";
+    const NO_UNDERLINE: &'static str = "\x1b[24m";
+    const STRIKE: &'static str = "\x1b[9m";
+    const NO_STRIKE: &'static str = "\x1b[29m";
+    const DIM: &'static str = "\x1b[2m";
+    const NO_DIM: &'static str = "\x1b[22m";
+    const REVERSE: &'static str = "\x1b[7m";
+    const NO_REVERSE: &'static str = "\x1b[27m";
+}
+
+impl BbcodeDialect for Term {
+    fn name(&self) -> &'static str {
+        "terminal preview"
+    }
+
+    fn heading_start(&self, _level: HeadingLevel) -> String {
+        format!("\n{}{}", Self::BOLD, Self::UNDERLINE)
+    }
+
+    fn heading_end(&self) -> String {
+        format!("{}{}\n", Self::NO_UNDERLINE, Self::NO_BOLD)
+    }
+
+    fn blockquote_start(&self) -> String {
+        Self::DIM.to_string()
+    }
+
+    fn blockquote_end(&self) -> String {
+        Self::NO_DIM.to_string()
+    }
+
+    fn code_block_start(&self, _lang: Option<&str>) -> String {
+        format!("\n{}", Self::REVERSE)
+    }
+
+    fn code_block_end(&self) -> String {
+        format!("{}\n", Self::NO_REVERSE)
+    }
+
+    fn list_start(&self, _ordered: bool) -> String {
+        String::new()
+    }
+
+    fn list_end(&self, _ordered: bool) -> String {
+        "\n".to_string()
+    }
+
+    fn item_start(&self) -> String {
+        "\n  • ".to_string()
+    }
+
+    fn item_end(&self) -> String {
+        String::new()
+    }
+
+    fn footnote_definition_start(&self, fnid: &str) -> String {
+        format!("\n{}[{fnid}]{} ", Self::DIM, Self::NO_DIM)
+    }
+
+    fn footnote_definition_end(&self) -> String {
+        "\n".to_string()
+    }
+
+    fn footnote_reference(&self, fnid: &str) -> String {
+        format!("{}[{fnid}]{}", Self::DIM, Self::NO_DIM)
+    }
+
+    fn table_start(&self) -> String {
+        "\n".to_string()
+    }
+
+    fn table_end(&self) -> String {
+        "\n".to_string()
+    }
+
+    fn table_head_start(&self) -> String {
+        Self::BOLD.to_string()
+    }
+
+    fn table_head_end(&self) -> String {
+        format!("{}\n", Self::NO_BOLD)
+    }
+
+    fn table_row_start(&self) -> String {
+        String::new()
+    }
+
+    fn table_row_end(&self) -> String {
+        "\n".to_string()
+    }
+
+    fn table_cell_start(&self) -> String {
+        String::new()
+    }
+
+    fn table_cell_end(&self) -> String {
+        "\t".to_string()
+    }
+
+    fn emphasis_start(&self) -> String {
+        Self::ITALIC.to_string()
+    }
+
+    fn emphasis_end(&self) -> String {
+        Self::NO_ITALIC.to_string()
+    }
+
+    fn strong_start(&self) -> String {
+        Self::BOLD.to_string()
+    }
+
+    fn strong_end(&self) -> String {
+        Self::NO_BOLD.to_string()
+    }
+
+    fn strikethrough_start(&self) -> String {
+        Self::STRIKE.to_string()
+    }
+
+    fn strikethrough_end(&self) -> String {
+        Self::NO_STRIKE.to_string()
+    }
+
+    fn superscript_start(&self) -> String {
+        Self::DIM.to_string()
+    }
+
+    fn superscript_end(&self) -> String {
+        Self::NO_DIM.to_string()
+    }
+
+    fn subscript_start(&self) -> String {
+        Self::DIM.to_string()
+    }
+
+    fn subscript_end(&self) -> String {
+        Self::NO_DIM.to_string()
+    }
+
+    fn code_inline(&self, code: &str) -> String {
+        format!("{}{code}{}", Self::REVERSE, Self::NO_REVERSE)
+    }
+
+    fn link_start(&self, url: &str) -> String {
+        // OSC-8 hyperlink: `ESC ] 8 ; ; <url> ST`.
+        format!("\x1b]8;;{url}\x1b\\{}", Self::UNDERLINE)
+    }
+
+    fn link_end(&self) -> String {
+        format!("{}\x1b]8;;\x1b\\", Self::NO_UNDERLINE)
+    }
+
+    fn image(&self, url: &str, title: &str) -> String {
+        if title.is_empty() {
+            format!("{}[image: {url}]{}", Self::DIM, Self::NO_DIM)
+        } else {
+            format!("{}[image: {title} ({url})]{}", Self::DIM, Self::NO_DIM)
+        }
+    }
+
+    fn rule(&self) -> String {
+        format!("\n{}{}{}\n", Self::DIM, "─".repeat(40), Self::NO_DIM)
+    }
+
+    fn spoiler_start(&self) -> Result<Option<String>> {
+        // No reveal-on-click in a terminal; just render the contents.
+        Ok(None)
+    }
+
+    fn spoiler_summary(&self, _summary_trimmed: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn spoiler_end(&self) -> Option<String> {
+        None
+    }
+
+    fn color_span(&self, rgb: (u8, u8, u8), text: &str) -> String {
+        // An ANSI 24-bit "truecolor" SGR foreground sequence; `[color]` is
+        // BBCode and would show up as literal junk in a terminal.
+        let (r, g, b) = rgb;
+        format!("\x1b[38;2;{r};{g};{b}m{text}\x1b[39m")
+    }
+}
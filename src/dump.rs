@@ -0,0 +1,35 @@
+//! A small subsystem, parallel to `convert::convert`, for inspecting the
+//! raw `pulldown_cmark` event stream a document produces. Mirrors comrak's
+//! `s-expr` example: instead of emitting BBCode, it pretty-prints the
+//! `Event`/`Tag` stream with nesting indentation for `Start`/`End` pairs,
+//! so bug reports can include exactly how a snippet was tokenized before
+//! translation.
+
+use pulldown_cmark::{Event, Options, Parser};
+use std::fmt::Write as _;
+
+pub fn dump_events<S: AsRef<str>>(input: S, markdown_opts: Options) -> String {
+    let input = input.as_ref();
+    let parser = Parser::new_ext(input, markdown_opts);
+
+    let mut out = String::new();
+    let mut depth = 0usize;
+
+    for event in parser {
+        match event {
+            Event::Start(tag) => {
+                writeln!(out, "{}{tag:?}", "  ".repeat(depth)).unwrap();
+                depth += 1;
+            }
+            Event::End(tag) => {
+                depth = depth.saturating_sub(1);
+                writeln!(out, "{}/{tag:?}", "  ".repeat(depth)).unwrap();
+            }
+            other => {
+                writeln!(out, "{}{other:?}", "  ".repeat(depth)).unwrap();
+            }
+        }
+    }
+
+    out
+}
@@ -0,0 +1,79 @@
+//! Lets a document pick its own dialect and parser options via a leading
+//! YAML-ish front-matter block, following cortav's model where the CLI's
+//! flags are only "weak" suggestions that a document can override.
+//!
+//! Only the small subset of YAML that `Args`' overridable fields need is
+//! understood: `key: value` lines, where `value` is a bare word (e.g.
+//! `xenforo`, `true`).
+
+use crate::args::Dialect;
+use anyhow::{bail, Result};
+
+#[derive(Default)]
+pub struct FrontMatter {
+    pub dialect: Option<Dialect>,
+    pub tables: Option<bool>,
+    pub footnotes: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub tasklists: Option<bool>,
+    pub smart_punctuation: Option<bool>,
+}
+
+/// Strips a leading `---`-fenced front-matter block off `input`, if
+/// present, and parses it. Returns the front matter (if any) alongside the
+/// remaining document body, with the block (and its fences) removed.
+pub fn extract(input: &str) -> Result<(Option<FrontMatter>, &str)> {
+    let Some(rest) = input.strip_prefix("---\n") else {
+        return Ok((None, input));
+    };
+
+    let Some(end) = rest.find("\n---") else {
+        bail!("Front matter opened with `---` but was never closed");
+    };
+
+    let block = &rest[..end];
+    let body = &rest[end + "\n---".len()..];
+    let body = body.strip_prefix('\n').unwrap_or(body);
+
+    let mut front_matter = FrontMatter::default();
+    for line in block.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            bail!("Malformed front-matter line: {line:?}");
+        };
+        let key = key.trim();
+        let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+
+        match key {
+            "dialect" => front_matter.dialect = Some(parse_dialect(value)?),
+            "tables" => front_matter.tables = Some(parse_bool(value)?),
+            "footnotes" => front_matter.footnotes = Some(parse_bool(value)?),
+            "strikethrough" => front_matter.strikethrough = Some(parse_bool(value)?),
+            "tasklists" => front_matter.tasklists = Some(parse_bool(value)?),
+            "smart_punctuation" => front_matter.smart_punctuation = Some(parse_bool(value)?),
+            _ => bail!("Unknown front-matter key: {key:?}"),
+        }
+    }
+
+    Ok((Some(front_matter), body))
+}
+
+fn parse_bool(value: &str) -> Result<bool> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => bail!("Expected `true` or `false` in front matter, found {value:?}"),
+    }
+}
+
+fn parse_dialect(value: &str) -> Result<Dialect> {
+    match value.to_ascii_lowercase().as_str() {
+        "xenforo" => Ok(Dialect::Xenforo),
+        "proboards" => Ok(Dialect::Proboards),
+        _ => bail!("Unknown dialect in front matter: {value:?}"),
+    }
+}
@@ -0,0 +1,64 @@
+//! A small port of rustdoc's `IdMap`/`derive_id` scheme for turning heading
+//! text into unique anchor slugs, so that two headings with the same text
+//! (e.g. two `## Overview` sections) don't collide.
+
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct IdMap {
+    counts: HashMap<String, usize>,
+}
+
+impl IdMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Computes a slug for `text` (lowercased, non-alphanumerics collapsed
+    /// to a single `-`, trimmed of leading/trailing `-`) and de-duplicates
+    /// it against earlier headings with the same base slug by appending
+    /// `-{n}`, where `n` counts those earlier headings. Only base slugs are
+    /// recorded, so a suffixed slug such as `overview-1` can still equal
+    /// the base slug of another heading.
+    pub fn derive_id(&mut self, text: &str) -> String {
+        let base = slugify(text);
+        let base = if base.is_empty() {
+            "section".to_string()
+        } else {
+            base
+        };
+
+        match self.counts.get_mut(&base) {
+            Some(count) => {
+                let id = format!("{base}-{count}");
+                *count += 1;
+                id
+            }
+            None => {
+                self.counts.insert(base.clone(), 1);
+                base
+            }
+        }
+    }
+}
+
+fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    let mut prev_dash = false;
+
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+            prev_dash = false;
+        } else if !prev_dash && !slug.is_empty() {
+            slug.push('-');
+            prev_dash = true;
+        }
+    }
+
+    if slug.ends_with('-') {
+        slug.pop();
+    }
+
+    slug
+}
@@ -1,17 +1,75 @@
 mod args;
 mod convert;
+mod dialect;
+mod dump;
+mod frontmatter;
+mod idmap;
+mod toc;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use args::Args;
 use clap::Parser;
+use frontmatter::FrontMatter;
 use pulldown_cmark::Options;
 use std::{
     fs::File,
     io::{self, prelude::*},
 };
 
+/// Folds a document's front matter into `args`, following cortav's model
+/// where the CLI's flags are only "weak" suggestions: by default the front
+/// matter wins whenever it sets a field, but with
+/// `--front-matter-defaults-only`, it only fills in fields this invocation
+/// didn't already turn on.
+fn apply_front_matter(args: &mut Args, front_matter: FrontMatter) {
+    let weak = args.front_matter_defaults_only;
+
+    if let Some(dialect) = front_matter.dialect {
+        if !weak || args.dialect.is_none() {
+            args.dialect = Some(dialect);
+        }
+    }
+
+    let merge = |flag: &mut bool, value: Option<bool>| {
+        if let Some(value) = value {
+            *flag = if weak { *flag || value } else { value };
+        }
+    };
+    merge(&mut args.tables, front_matter.tables);
+    merge(&mut args.footnotes, front_matter.footnotes);
+    merge(&mut args.strikethrough, front_matter.strikethrough);
+    merge(&mut args.tasklists, front_matter.tasklists);
+    merge(&mut args.smart_punctuation, front_matter.smart_punctuation);
+}
+
 fn main() -> Result<()> {
     // Process command line arguments.
-    let args = args::Args::parse();
+    let mut args = args::Args::parse();
+
+    // Read contents of input file into memory.
+    let mut input_string = String::new();
+    if let Some(input_path) = &args.input {
+        File::open(input_path)?.read_to_string(&mut input_string)?;
+    } else {
+        io::stdin().lock().read_to_string(&mut input_string)?;
+    }
+
+    // A document may carry its own front-matter block declaring which
+    // dialect/options to use; strip it off and fold it into `args` before
+    // the body is handed to the Markdown parser.
+    let (front_matter, body) = frontmatter::extract(&input_string)?;
+    let body = body.to_string();
+    if let Some(front_matter) = front_matter {
+        apply_front_matter(&mut args, front_matter);
+    }
+
+    // `--preview` is shorthand for `--dialect term`; it wins over whatever
+    // the command line or front matter asked for, since it's an explicit
+    // request to render for the terminal rather than any forum.
+    if args.preview {
+        args.dialect = Some(args::Dialect::Term);
+    }
+
     let markdown_opts = {
         let mut opts = Options::empty();
 
@@ -34,21 +92,27 @@ fn main() -> Result<()> {
         opts
     };
 
-    // Read contents of input file into memory.
-    let mut input_string = String::new();
-    if let Some(input_path) = args.input {
-        File::open(input_path)?.read_to_string(&mut input_string)?;
+    // Perform the actual conversion, or just dump the event stream if the
+    // caller is debugging how a document gets tokenized.
+    let output_string = if args.dump_events {
+        dump::dump_events(body, markdown_opts)
     } else {
-        io::stdin().lock().read_to_string(&mut input_string)?;
-    }
+        let dialect = args.dialect.ok_or_else(|| {
+            anyhow!(
+                "No dialect given: pass --dialect, or declare `dialect:` in \
+                 the document's front matter"
+            )
+        })?;
 
-    // Perform the actual conversion.
-    let output_string = convert::convert(
-        input_string,
-        args.dialect,
-        args.encoding_warnings,
-        markdown_opts,
-    )?;
+        convert::convert(
+            body,
+            dialect,
+            args.encoding_warnings,
+            args.highlight,
+            args.toc,
+            markdown_opts,
+        )?
+    };
     let output_str = output_string.trim();
 
     // Write to the output file.
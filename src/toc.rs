@@ -0,0 +1,64 @@
+//! Builds a nested table of contents out of the headings collected while
+//! converting a document, mirroring a `TocBuilder` stack keyed on
+//! `HeadingLevel`: every heading deeper than the current top opens a new
+//! sublist, every heading at or above it closes sublists back down first.
+
+use crate::dialect::BbcodeDialect;
+use pulldown_cmark::HeadingLevel;
+
+/// One heading collected while walking the event stream: its level, its
+/// (already de-duplicated) anchor slug, and its plain text.
+pub type TocEntry = (HeadingLevel, String, String);
+
+pub fn render(dialect: &dyn BbcodeDialect, entries: &[TocEntry]) -> String {
+    let mut out = String::new();
+    let mut stack: Vec<HeadingLevel> = Vec::new();
+
+    for (level, slug, text) in entries {
+        let level = *level;
+
+        match stack.last().copied() {
+            None => {
+                out.push_str(&dialect.list_start(false));
+                stack.push(level);
+            }
+            Some(top) if level > top => {
+                out.push_str(&dialect.list_start(false));
+                stack.push(level);
+            }
+            Some(top) if level == top => {
+                out.push_str(&dialect.item_end());
+            }
+            Some(_) => {
+                while let Some(&top) = stack.last() {
+                    if top <= level {
+                        break;
+                    }
+                    out.push_str(&dialect.item_end());
+                    out.push_str(&dialect.list_end(false));
+                    stack.pop();
+                }
+
+                match stack.last() {
+                    Some(&top) if top == level => out.push_str(&dialect.item_end()),
+                    _ => {
+                        out.push_str(&dialect.list_start(false));
+                        stack.push(level);
+                    }
+                }
+            }
+        }
+
+        out.push_str(&dialect.item_start());
+        out.push_str(&dialect.link_start(&format!("#{slug}")));
+        out.push_str(text);
+        out.push_str(&dialect.link_end());
+    }
+
+    while stack.pop().is_some() {
+        out.push_str(&dialect.item_end());
+        out.push_str(&dialect.list_end(false));
+    }
+
+    out
+}